@@ -0,0 +1,190 @@
+/*
+  Configurable drum-map subsystem: a table of note_number -> Instrument.
+
+  Mirrors the "drummap" concept used by desktop MIDI editors. Each supported
+  kit ships as a built-in map (the tables that used to be hard-coded in
+  `midi_input_handler.rs`), and users can point at their own TOML/JSON file
+  to support a kit that isn't built in, without recompiling.
+*/
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::midi_input_handler::Instrument;
+
+#[derive(Debug, Deserialize)]
+struct DrumMapFile {
+    #[serde(flatten)]
+    notes: HashMap<String, Vec<u8>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DrumMap {
+    note_to_instrument: HashMap<u8, Instrument>,
+}
+
+impl DrumMap {
+    /// Load a drum map from a user-supplied TOML or JSON file. The format is
+    /// looked up by the file extension (anything that isn't `.json` is
+    /// parsed as TOML). Each key is an instrument name (matching the
+    /// `Instrument` variants, e.g. `closed_hihat`) and each value is the
+    /// list of note numbers that should trigger it, e.g.:
+    ///
+    /// ```toml
+    /// kick = [36]
+    /// snare = [38, 40, 37]
+    /// ```
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, DrumMapError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        let file: DrumMapFile = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str(&contents)?,
+            _ => toml::from_str(&contents)?,
+        };
+
+        let mut note_to_instrument = HashMap::new();
+        for (name, notes) in file.notes {
+            let instrument = instrument_from_name(&name)
+                .ok_or_else(|| DrumMapError::UnknownInstrument(name.clone()))?;
+            for note in notes {
+                note_to_instrument.insert(note, instrument);
+            }
+        }
+        Ok(Self { note_to_instrument })
+    }
+
+    /// Look up the instrument a given MIDI note number is mapped to.
+    pub fn lookup(&self, note: u8) -> Option<Instrument> {
+        self.note_to_instrument.get(&note).copied()
+    }
+
+    /// Pick one of the built-in maps by (partial) device name, falling back
+    /// to the TD-27 map for anything unrecognized.
+    pub fn for_device_name(device_name: &str) -> Self {
+        match device_name {
+            s if s == "MPK Mini Mk II" => Self::mpk_mini_mk_ii(),
+            s if s.contains("TD-17") => Self::td17(),
+            s if s.contains("TD-27") => Self::td27(),
+            s if s.contains("Nitro") => Self::alesis_nitro(),
+            _ => {
+                log::warn!("warning: unknown midi device, using default of 'td27'");
+                Self::td27()
+            }
+        }
+    }
+
+    fn from_pairs(pairs: Vec<(Instrument, Vec<u8>)>) -> Self {
+        let mut note_to_instrument = HashMap::new();
+        for (instrument, notes) in pairs {
+            for note in notes {
+                note_to_instrument.insert(note, instrument);
+            }
+        }
+        Self { note_to_instrument }
+    }
+
+    // midi device: "MPK Mini Mk II"
+    fn mpk_mini_mk_ii() -> Self {
+        Self::from_pairs(vec![
+            (Instrument::ClosedHihat, vec![44, 48]),
+            (Instrument::Snare, vec![45, 49]),
+            (Instrument::Kick, vec![46, 50]),
+            (Instrument::OpenHihat, vec![47, 51]),
+        ])
+    }
+
+    // https://support.roland.com/hc/en-us/articles/360005173411-TD-17-Default-Factory-MIDI-Note-Map
+    fn td17() -> Self {
+        Self::roland_td_map()
+    }
+
+    // https://support.roland.com/hc/en-us/articles/4407474950811-TD-27-Default-MIDI-Note-Map
+    fn td27() -> Self {
+        Self::roland_td_map()
+    }
+
+    fn roland_td_map() -> Self {
+        Self::from_pairs(vec![
+            (Instrument::ClosedHihat, vec![42, 22]),
+            (Instrument::Snare, vec![38, 40, 37]),
+            (Instrument::Kick, vec![36]),
+            (Instrument::OpenHihat, vec![46, 26]),
+            (Instrument::Ride, vec![51, 53, 59]),
+            (Instrument::Crash, vec![49, 55, 57, 52]),
+            (Instrument::Tom1, vec![50, 48]),
+            (Instrument::Tom2, vec![47, 45]),
+            (Instrument::Tom3, vec![58, 43]),
+            (Instrument::PedalHiHat, vec![44]),
+        ])
+    }
+
+    fn alesis_nitro() -> Self {
+        Self::from_pairs(vec![
+            (Instrument::ClosedHihat, vec![42]),
+            (Instrument::Snare, vec![38]),
+            (Instrument::Kick, vec![36]),
+            (Instrument::OpenHihat, vec![46, 23]),
+        ])
+    }
+}
+
+fn instrument_from_name(name: &str) -> Option<Instrument> {
+    match name {
+        "closed_hihat" => Some(Instrument::ClosedHihat),
+        "snare" => Some(Instrument::Snare),
+        "kick" => Some(Instrument::Kick),
+        "open_hihat" => Some(Instrument::OpenHihat),
+        "pedal_hihat" => Some(Instrument::PedalHiHat),
+        "ride" => Some(Instrument::Ride),
+        "tom_1" => Some(Instrument::Tom1),
+        "tom_2" => Some(Instrument::Tom2),
+        "tom_3" => Some(Instrument::Tom3),
+        "crash" => Some(Instrument::Crash),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+pub enum DrumMapError {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    UnknownInstrument(String),
+}
+
+impl fmt::Display for DrumMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DrumMapError::Io(e) => write!(f, "couldn't read drum map file: {e}"),
+            DrumMapError::Toml(e) => write!(f, "couldn't parse drum map as toml: {e}"),
+            DrumMapError::Json(e) => write!(f, "couldn't parse drum map as json: {e}"),
+            DrumMapError::UnknownInstrument(name) => {
+                write!(f, "drum map references unknown instrument '{name}'")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DrumMapError {}
+
+impl From<std::io::Error> for DrumMapError {
+    fn from(e: std::io::Error) -> Self {
+        DrumMapError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for DrumMapError {
+    fn from(e: toml::de::Error) -> Self {
+        DrumMapError::Toml(e)
+    }
+}
+
+impl From<serde_json::Error> for DrumMapError {
+    fn from(e: serde_json::Error) -> Self {
+        DrumMapError::Json(e)
+    }
+}
@@ -1,9 +1,17 @@
+mod drum_map;
 mod midi;
 mod midi_input_handler;
+mod midi_message;
+mod midi_output;
+mod quantize;
+mod smf;
 mod time;
 
 use macroquad::prelude::*;
 use midi_input_handler::{Events, MidiInputHandler};
+use midi_output::MidiOutput;
+use quantize::BeatGrid;
+use smf::Recorder;
 use time::current_time_millis;
 
 fn mq_get_time_ms() -> u128 {
@@ -14,6 +22,15 @@ fn mq_get_time_ms() -> u128 {
 // we want to compare a the timestamp of a game frame
 // real - midi = actual_midi
 
+// TODO: read this from a config / let the player set it
+const DEFAULT_BPM: f64 = 120.;
+
+// General MIDI percussion channel (channel 10, 0-indexed) and Claves note,
+// used for the metronome click and thru echo
+const METRONOME_CHANNEL: u8 = 9;
+const METRONOME_NOTE: u8 = 75;
+const METRONOME_VELOCITY: u8 = 100;
+
 #[macroquad::main("MyGame")]
 async fn main() {
     let real_start_time = current_time_millis();
@@ -24,11 +41,24 @@ async fn main() {
 
     let mut max_diff = 0;
     let mut midi_input = MidiInputHandler::new();
+    let beat_grid = BeatGrid::new(real_start_time, DEFAULT_BPM);
+    let mut recorder = Recorder::new();
+    let mut midi_output = MidiOutput::new();
+    let mut last_clicked_beat: Option<u32> = None;
     loop {
         let real_time = current_time_millis();
         let mqtime: u128 = mq_get_time_ms();
         // get_frame_time() // potentially useful to see if frame times are uneven
 
+        // click the metronome once per beat on the grid
+        let current_beat = beat_grid.current_beat(real_time);
+        if last_clicked_beat != Some(current_beat) {
+            last_clicked_beat = Some(current_beat);
+            if let Some(midi_output) = midi_output.as_mut() {
+                midi_output.send_click(METRONOME_CHANNEL, METRONOME_NOTE, METRONOME_VELOCITY);
+            }
+        }
+
         let events = midi_input.process();
 
         if !events.is_empty() {
@@ -38,14 +68,24 @@ async fn main() {
             for e in events {
                 match e {
                     Events::Hit(h) => {
-                        println!("hit .. realtime: {:?}", h.raw_data.non_midi_timestamp_ms);
+                        println!("hit .. realtime: {:?}", h.hit.onset_timestamp_ms);
                         let diff_midi_hit_to_frame_time: i128 =
-                            real_time as i128 - h.raw_data.non_midi_timestamp_ms as i128;
+                            real_time as i128 - h.hit.onset_timestamp_ms as i128;
                         println!("diff = {}", diff_midi_hit_to_frame_time);
                         if diff_midi_hit_to_frame_time > max_diff {
                             max_diff = diff_midi_hit_to_frame_time;
                         }
                         println!("max_diff = {}", max_diff);
+
+                        let quantized = beat_grid.quantize(&h);
+                        println!(
+                            "quantized .. beat {} error_ms {:.1}",
+                            quantized.beat_index, quantized.error_ms
+                        );
+
+                        if let Some(midi_output) = midi_output.as_mut() {
+                            midi_output.echo_hit(METRONOME_CHANNEL, &h.hit);
+                        }
                     }
                 }
                 // TODO: Can I surface the exact midi input timing (maybe just via modifying my local)
@@ -58,6 +98,35 @@ async fn main() {
             // TODO: Can I surface the exact keyboard input timing (hack / vendor macroquad lib)
         }
 
+        for hit in midi_input.take_completed_hits() {
+            recorder.record_hit(&hit);
+        }
+
+        for transport_event in midi_input.take_transport_events() {
+            println!("transport event: {:?}", transport_event);
+        }
+        if let Some(external_bpm) = midi_input.get_external_bpm() {
+            println!("external bpm: {:.1}", external_bpm);
+        }
+        for other_message in midi_input.take_other_messages() {
+            println!("other channel message: {:?}", other_message);
+        }
+        for sysex_payload in midi_input.take_sysex_messages() {
+            println!("sysex payload ({} bytes): {:?}", sysex_payload.len(), sysex_payload);
+        }
+
+        if is_key_pressed(KeyCode::Escape) {
+            if !recorder.is_empty() {
+                if let Err(e) = recorder.export_smf("session.mid", DEFAULT_BPM) {
+                    println!("failed to export session.mid: {e}");
+                }
+            }
+            if let Some(midi_output) = midi_output.as_mut() {
+                midi_output.panic();
+            }
+            break;
+        }
+
         next_frame().await
     }
 }
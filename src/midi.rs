@@ -10,6 +10,7 @@ use std::collections::HashMap;
 use std::string::*;
 use std::sync::{Arc, Mutex};
 
+use crate::midi_message::{parse_message, ChannelMessage, OtherChannelMessage, ParsedMidiMessage};
 use crate::time::current_time_millis;
 
 pub struct MidiInput {
@@ -21,6 +22,79 @@ pub struct MidiInput {
 
     raw_inputs: Arc<Mutex<HashMap<u8, MidiInputDataRaw>>>,
     previous_raw_inputs: Arc<Mutex<HashMap<u8, MidiInputDataRaw>>>,
+
+    // note-on events waiting on a matching note-off, keyed by note number
+    pending_hits: Arc<Mutex<HashMap<u8, PendingHit>>>,
+    onset_hits: Arc<Mutex<Vec<OnsetHit>>>,
+    completed_hits: Arc<Mutex<Vec<CompletedHit>>>,
+
+    clock_sync: Arc<Mutex<ClockSync>>,
+    transport_events: Arc<Mutex<Vec<TransportEvent>>>,
+
+    other_messages: Arc<Mutex<Vec<OtherChannelMessage>>>,
+    sysex_messages: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+// status bytes 0xF8-0xFF are System Real-Time messages: always a single
+// byte, sent continuously while a clock source is running, so they're
+// handled separately from channel-voice messages.
+const TIMING_CLOCK: u8 = 0xF8;
+const TRANSPORT_START: u8 = 0xFA;
+const TRANSPORT_CONTINUE: u8 = 0xFB;
+const TRANSPORT_STOP: u8 = 0xFC;
+
+const PULSES_PER_QUARTER_NOTE: u8 = 24;
+
+// a drum hit longer than this isn't a held pad/hi-hat-pedal, it's a device
+// that never sent a note-off (or dropped it); evict it in `flush()` instead
+// of letting `pending_hits` grow forever.
+const PENDING_HIT_TIMEOUT_MS: u128 = 5_000;
+
+/// Start/Stop/Continue, as sent by an external clock source (a drum machine
+/// or DAW) to control playback transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportEvent {
+    Start,
+    Stop,
+    Continue,
+}
+
+#[derive(Default)]
+struct ClockSync {
+    // pulses seen since the last quarter-note boundary; a BPM sample is
+    // only taken once this wraps back to 0, i.e. once per quarter note
+    // (PULSES_PER_QUARTER_NOTE pulses), not from a single inter-pulse gap
+    pulse_count: u8,
+    last_quarter_note_timestamp_ms: Option<u128>,
+    smoothed_bpm: Option<f64>,
+}
+
+impl ClockSync {
+    // exponential moving average weight for each new quarter-note sample;
+    // low enough to ride out jitter but still track tempo changes
+    const SMOOTHING_ALPHA: f64 = 0.1;
+
+    fn on_pulse(&mut self, now_ms: u128) {
+        self.pulse_count += 1;
+        if self.pulse_count < PULSES_PER_QUARTER_NOTE {
+            return;
+        }
+        self.pulse_count = 0;
+
+        if let Some(last_ms) = self.last_quarter_note_timestamp_ms {
+            let ms_per_quarter_note = now_ms.saturating_sub(last_ms) as f64;
+            if ms_per_quarter_note > 0. {
+                let bpm_sample = 60_000. / ms_per_quarter_note;
+                self.smoothed_bpm = Some(match self.smoothed_bpm {
+                    Some(prev) => {
+                        Self::SMOOTHING_ALPHA * bpm_sample + (1. - Self::SMOOTHING_ALPHA) * prev
+                    }
+                    None => bpm_sample,
+                });
+            }
+        }
+        self.last_quarter_note_timestamp_ms = Some(now_ms);
+    }
 }
 
 #[derive(Eq, Clone, Debug, Copy, PartialEq)]
@@ -34,9 +108,70 @@ pub struct MidiInputDataRaw {
 }
 
 impl MidiInputDataRaw {
+    pub(crate) fn new(
+        note_number: u8,
+        timestamp: u64,
+        non_midi_timestamp_ms: u128,
+        status: u8,
+        note_velocity: u8,
+    ) -> Self {
+        Self {
+            note_number,
+            timestamp,
+            non_midi_timestamp_ms,
+            status,
+            note_velocity,
+        }
+    }
+
     pub fn is_note_on(&self) -> bool {
-        self.status >= 144 && self.status <= 159
+        self.status >= 144 && self.status <= 159 && self.note_velocity > 0
     }
+
+    pub fn is_note_off(&self) -> bool {
+        (self.status >= 128 && self.status <= 143)
+            || (self.status >= 144 && self.status <= 159 && self.note_velocity == 0)
+    }
+
+    pub fn velocity(&self) -> u8 {
+        self.note_velocity
+    }
+
+    pub(crate) fn status_byte(&self) -> u8 {
+        self.status
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct PendingHit {
+    onset_timestamp_ms: u128,
+    velocity: u8,
+}
+
+/// A note-on, surfaced the instant it arrives rather than waiting for the
+/// matching note-off. This is what game-facing code should consume for
+/// timing: a `CompletedHit` would delay registration by the full hold
+/// duration for held pads/hi-hat-pedal, and devices that never emit a
+/// note-off would never surface a hit at all.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OnsetHit {
+    pub note_number: u8,
+    pub velocity: u8,
+    pub onset_timestamp_ms: u128,
+}
+
+/// A note-on paired with its matching note-off, i.e. a completed drum hit.
+/// Knows how long a pad was held (useful for cymbal chokes / hi-hat pedal
+/// state, or recording/export), which a bare note-on can't tell you - but
+/// isn't available until release, so it's not suitable for latency-sensitive
+/// game timing (see `OnsetHit`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompletedHit {
+    pub note_number: u8,
+    pub velocity: u8,
+    pub onset_timestamp_ms: u128,
+    pub release_timestamp_ms: u128,
+    pub duration_ms: u128,
 }
 
 impl MidiInput {
@@ -59,21 +194,56 @@ impl MidiInput {
             connection: None,
             raw_inputs: Arc::new(Mutex::new(HashMap::with_capacity(16))),
             previous_raw_inputs: Arc::new(Mutex::new(HashMap::with_capacity(16))),
+            pending_hits: Arc::new(Mutex::new(HashMap::with_capacity(16))),
+            onset_hits: Arc::new(Mutex::new(Vec::new())),
+            completed_hits: Arc::new(Mutex::new(Vec::new())),
+            clock_sync: Arc::new(Mutex::new(ClockSync::default())),
+            transport_events: Arc::new(Mutex::new(Vec::new())),
+            other_messages: Arc::new(Mutex::new(Vec::new())),
+            sysex_messages: Arc::new(Mutex::new(Vec::new())),
         })
     }
 
-    pub fn get_pressed_buttons(&self) -> Vec<MidiInputDataRaw> {
-        let mut pressed = Vec::new();
-        let mut raw_inputs = self.raw_inputs.lock().unwrap();
-        for (_id, raw_input) in raw_inputs.iter_mut() {
-            if raw_input.is_note_on() {
-                pressed.push(*raw_input);
-            }
-        }
-        if pressed.len() > 0 {
-            log::info!("Pressed midi: {:?}", pressed);
-        }
-        pressed
+    /// drain and return the non-note channel-voice messages (control
+    /// change, pitch bend, program change, aftertouch) received since the
+    /// last call
+    pub fn take_other_messages(&mut self) -> Vec<OtherChannelMessage> {
+        let mut other_messages = self.other_messages.lock().unwrap();
+        std::mem::take(&mut *other_messages)
+    }
+
+    /// drain and return the raw System Exclusive payloads received since
+    /// the last call
+    pub fn take_sysex_messages(&mut self) -> Vec<Vec<u8>> {
+        let mut sysex_messages = self.sysex_messages.lock().unwrap();
+        std::mem::take(&mut *sysex_messages)
+    }
+
+    /// BPM derived from the external MIDI clock (Timing Clock pulses);
+    /// `None` until two full quarter notes (48 pulses) have been received.
+    pub fn get_external_bpm(&self) -> Option<f64> {
+        self.clock_sync.lock().unwrap().smoothed_bpm
+    }
+
+    /// drain and return the Start/Stop/Continue transport events received
+    /// since the last call
+    pub fn take_transport_events(&mut self) -> Vec<TransportEvent> {
+        let mut transport_events = self.transport_events.lock().unwrap();
+        std::mem::take(&mut *transport_events)
+    }
+
+    /// drain and return the hits (note-on paired with note-off) completed
+    /// since the last call
+    pub fn take_completed_hits(&mut self) -> Vec<CompletedHit> {
+        let mut completed_hits = self.completed_hits.lock().unwrap();
+        std::mem::take(&mut *completed_hits)
+    }
+
+    /// drain and return the note-on hits received since the last call,
+    /// surfaced immediately rather than waiting for a matching note-off
+    pub fn take_onset_hits(&mut self) -> Vec<OnsetHit> {
+        let mut onset_hits = self.onset_hits.lock().unwrap();
+        std::mem::take(&mut *onset_hits)
     }
 
     // clear all inputs, update previous values
@@ -89,11 +259,27 @@ impl MidiInput {
             }
         }
         raw_inputs.clear();
+
+        // a device that never sends note-off (or drops one) would otherwise
+        // leak its pending_hits entry forever; evict anything that's been
+        // waiting too long to plausibly still be a held note
+        let now_ms = current_time_millis();
+        let mut pending_hits = self.pending_hits.lock().unwrap();
+        pending_hits
+            .retain(|_, pending| now_ms.saturating_sub(pending.onset_timestamp_ms) < PENDING_HIT_TIMEOUT_MS);
     }
 
     pub fn connect(&mut self) {
         log::info!("Connecting to midi device: {}", self.device_name);
         let raw_inputs = self.raw_inputs.clone();
+        let pending_hits = self.pending_hits.clone();
+        let onset_hits = self.onset_hits.clone();
+        let completed_hits = self.completed_hits.clone();
+        let clock_sync = self.clock_sync.clone();
+        let transport_events = self.transport_events.clone();
+        let other_messages = self.other_messages.clone();
+        let sysex_messages = self.sysex_messages.clone();
+        let mut running_status: Option<u8> = None;
         self.connection = Some(
             self.midi_input
                 .take() // consume midi_input because it will be sent to thread
@@ -102,22 +288,86 @@ impl MidiInput {
                     &self.input_port,
                     self.device_name.as_str(),
                     move |stamp, message, _| {
-                        // get timestamp
                         let non_midi_timestamp_ms = current_time_millis();
-                        let midi_function = message[0];
-                        let note_number = message[1];
-                        let v = MidiInputDataRaw {
-                            note_number,
-                            timestamp: stamp,
+                        info!("{}: {:?} (len = {})", stamp, message, message.len());
+
+                        match parse_message(
+                            message,
+                            &mut running_status,
+                            stamp,
                             non_midi_timestamp_ms,
-                            status: midi_function,
-                            note_velocity: message[2],
-                        };
-                        info!("{}: {:?} (len = {})", stamp, v, message.len());
-                        info!("{}", MIDI_FUNCTION_NAMES[midi_function as usize - 128]);
-                        let mut rw: std::sync::MutexGuard<HashMap<u8, MidiInputDataRaw>> =
-                            raw_inputs.lock().unwrap();
-                        rw.insert(note_number, v);
+                        ) {
+                            ParsedMidiMessage::RealTime(status) => {
+                                info!("{}", MIDI_FUNCTION_NAMES[status as usize - 128]);
+                                match status {
+                                    TIMING_CLOCK => clock_sync
+                                        .lock()
+                                        .unwrap()
+                                        .on_pulse(non_midi_timestamp_ms),
+                                    TRANSPORT_START => transport_events
+                                        .lock()
+                                        .unwrap()
+                                        .push(TransportEvent::Start),
+                                    TRANSPORT_CONTINUE => transport_events
+                                        .lock()
+                                        .unwrap()
+                                        .push(TransportEvent::Continue),
+                                    TRANSPORT_STOP => transport_events
+                                        .lock()
+                                        .unwrap()
+                                        .push(TransportEvent::Stop),
+                                    _ => {}
+                                }
+                            }
+                            ParsedMidiMessage::SysEx(payload) => {
+                                sysex_messages.lock().unwrap().push(payload);
+                            }
+                            ParsedMidiMessage::SystemCommon => {}
+                            ParsedMidiMessage::Incomplete => {
+                                log::warn!(
+                                    "warning: couldn't parse midi message {:?}, dropping it",
+                                    message
+                                );
+                            }
+                            ParsedMidiMessage::Channel(ChannelMessage::Other(msg)) => {
+                                other_messages.lock().unwrap().push(msg);
+                            }
+                            ParsedMidiMessage::Channel(ChannelMessage::Note(v)) => {
+                                info!("{}", MIDI_FUNCTION_NAMES[v.status_byte() as usize - 128]);
+                                let note_number = v.note_number;
+
+                                if v.is_note_on() {
+                                    pending_hits.lock().unwrap().insert(
+                                        note_number,
+                                        PendingHit {
+                                            onset_timestamp_ms: non_midi_timestamp_ms,
+                                            velocity: v.velocity(),
+                                        },
+                                    );
+                                    onset_hits.lock().unwrap().push(OnsetHit {
+                                        note_number,
+                                        velocity: v.velocity(),
+                                        onset_timestamp_ms: non_midi_timestamp_ms,
+                                    });
+                                } else if v.is_note_off() {
+                                    let mut pending = pending_hits.lock().unwrap();
+                                    if let Some(pending_hit) = pending.remove(&note_number) {
+                                        completed_hits.lock().unwrap().push(CompletedHit {
+                                            note_number,
+                                            velocity: pending_hit.velocity,
+                                            onset_timestamp_ms: pending_hit.onset_timestamp_ms,
+                                            release_timestamp_ms: non_midi_timestamp_ms,
+                                            duration_ms: non_midi_timestamp_ms
+                                                .saturating_sub(pending_hit.onset_timestamp_ms),
+                                        });
+                                    }
+                                }
+
+                                let mut rw: std::sync::MutexGuard<HashMap<u8, MidiInputDataRaw>> =
+                                    raw_inputs.lock().unwrap();
+                                rw.insert(note_number, v);
+                            }
+                        }
                     },
                     (),
                 )
@@ -130,6 +380,57 @@ impl MidiInput {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed_pulses_at_ms_per_pulse(clock_sync: &mut ClockSync, start_ms: u128, ms_per_pulse: u128, quarter_notes: u32) {
+        let mut now_ms = start_ms;
+        for _ in 0..(quarter_notes * PULSES_PER_QUARTER_NOTE as u32) {
+            clock_sync.on_pulse(now_ms);
+            now_ms += ms_per_pulse;
+        }
+    }
+
+    #[test]
+    fn on_pulse_does_not_sample_bpm_until_a_full_quarter_note_of_pulses() {
+        let mut clock_sync = ClockSync::default();
+        for i in 0..(PULSES_PER_QUARTER_NOTE - 1) {
+            clock_sync.on_pulse(i as u128);
+            assert_eq!(clock_sync.smoothed_bpm, None);
+        }
+    }
+
+    #[test]
+    fn on_pulse_derives_bpm_from_a_full_quarter_note_boundary() {
+        let mut clock_sync = ClockSync::default();
+        // 24 pulses at 20ms/pulse = 480ms per quarter note = 125 bpm; the
+        // first quarter note only establishes the boundary, the second
+        // yields the first sample
+        feed_pulses_at_ms_per_pulse(&mut clock_sync, 0, 20, 2);
+        let bpm = clock_sync.smoothed_bpm.expect("bpm should be sampled");
+        assert!((bpm - 125.).abs() < 0.01, "expected ~125 bpm, got {bpm}");
+    }
+
+    #[test]
+    fn on_pulse_smooths_across_quarter_notes_instead_of_snapping() {
+        let mut clock_sync = ClockSync::default();
+        feed_pulses_at_ms_per_pulse(&mut clock_sync, 0, 20, 2); // settles at ~125 bpm
+        let first_bpm = clock_sync.smoothed_bpm.unwrap();
+
+        // one quarter note whose boundary lands 250ms after the last one
+        // (940ms -> 1190ms), i.e. a 240 bpm sample
+        feed_pulses_at_ms_per_pulse(&mut clock_sync, 960, 10, 1);
+        let second_bpm = clock_sync.smoothed_bpm.unwrap();
+
+        assert!(second_bpm > first_bpm);
+        assert!(
+            second_bpm < 240.,
+            "a single sample shouldn't snap straight to the new tempo, got {second_bpm}"
+        );
+    }
+}
+
 // Midi Spec
 
 // from 128-255, these are the functions corresponding to a Midi Note's 1st byte
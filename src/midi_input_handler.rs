@@ -5,11 +5,12 @@
   and flushing the internally stored events after the have been consumed via process().
 */
 
-use std::collections::HashSet;
+use std::path::Path;
 
 use macroquad::prelude::*;
 
-use crate::midi::{MidiInput, MidiInputDataRaw};
+use crate::drum_map::DrumMap;
+use crate::midi::{CompletedHit, MidiInput, OnsetHit};
 
 // General use
 pub const ALL_INSTRUMENTS: [Instrument; 10] = [
@@ -32,7 +33,11 @@ pub const BEATS_PER_LOOP: f64 = 16.;
 #[derive(Debug, Clone)]
 pub struct UserHit {
     pub instrument: Instrument,
-    pub raw_data: MidiInputDataRaw,
+    // surfaced immediately on note-on, not delayed until note-off - a held
+    // pad (or a device that never sends note-off) would otherwise register
+    // late or never; use `MidiInputHandler::take_completed_hits` instead if
+    // you need hold duration (e.g. recording/export)
+    pub hit: OnsetHit,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -56,19 +61,48 @@ pub enum Events {
 
 pub struct MidiInputHandler {
     midi_input: Option<MidiInput>,
+    drum_map: DrumMap,
 }
 
 impl MidiInputHandler {
+    /// Connect to the first midi input device found, picking a built-in
+    /// drum map by device name.
     pub fn new() -> Self {
         let mut midi_input = MidiInput::new();
-        match midi_input {
+        let drum_map = match midi_input {
             Some(ref mut midi_input) => {
                 midi_input.connect();
+                DrumMap::for_device_name(midi_input.get_device_name())
             }
-            None => log::warn!("warning: no midi input device found"),
+            None => {
+                log::warn!("warning: no midi input device found");
+                DrumMap::for_device_name("")
+            }
+        };
+
+        Self {
+            midi_input,
+            drum_map,
+        }
+    }
+
+    /// Connect to the first midi input device found, but load the drum map
+    /// from a user-supplied TOML/JSON file instead of picking one by device
+    /// name.
+    pub fn new_with_drum_map_file<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<Self, crate::drum_map::DrumMapError> {
+        let mut midi_input = MidiInput::new();
+        if let Some(ref mut midi_input) = midi_input {
+            midi_input.connect();
+        } else {
+            log::warn!("warning: no midi input device found");
         }
 
-        Self { midi_input }
+        Ok(Self {
+            midi_input,
+            drum_map: DrumMap::from_file(path)?,
+        })
     }
 
     /// convert any user input from the last frame into Events
@@ -79,7 +113,7 @@ impl MidiInputHandler {
         // let _now_ms = current_time_millis();
         match &mut self.midi_input {
             Some(midi_input) => {
-                let hits = get_midi_as_user_hits(midi_input);
+                let hits = get_midi_as_user_hits(midi_input, &self.drum_map);
 
                 // for each hit, calculate the processing delay and correct the clock time
                 for hit in &hits {
@@ -96,119 +130,62 @@ impl MidiInputHandler {
 
         events
     }
-}
 
-struct InputConfigMidi {
-    kick: HashSet<u8>,
-    snare: HashSet<u8>,
-    closed_hi_hat: HashSet<u8>,
-    open_hi_hat: HashSet<u8>,
-    ride: HashSet<u8>,
-    crash: HashSet<u8>,
-    tom_1: HashSet<u8>,
-    tom_2: HashSet<u8>,
-    tom_3: HashSet<u8>,
-    pedal_hihat: HashSet<u8>,
-}
+    /// drain and return hits completed (note-on paired with note-off) since
+    /// the last call; unlike the onset-only `UserHit`s from `process()`,
+    /// these carry hold duration, e.g. for recording/export
+    pub fn take_completed_hits(&mut self) -> Vec<CompletedHit> {
+        match &mut self.midi_input {
+            Some(midi_input) => midi_input.take_completed_hits(),
+            None => Vec::new(),
+        }
+    }
+
+    /// drain and return the Start/Stop/Continue transport events received
+    /// since the last call
+    pub fn take_transport_events(&mut self) -> Vec<crate::midi::TransportEvent> {
+        match &mut self.midi_input {
+            Some(midi_input) => midi_input.take_transport_events(),
+            None => Vec::new(),
+        }
+    }
 
-impl InputConfigMidi {
-    pub fn get_note_numbers(self: &Self, ins: &Instrument) -> &HashSet<u8> {
-        match ins {
-            Instrument::ClosedHihat => &self.closed_hi_hat,
-            Instrument::Snare => &self.snare,
-            Instrument::Kick => &self.kick,
-            Instrument::OpenHihat => &self.open_hi_hat,
-            Instrument::Ride => &self.ride,
-            Instrument::Crash => &self.crash,
-            Instrument::Tom1 => &self.tom_1,
-            Instrument::Tom2 => &self.tom_2,
-            Instrument::Tom3 => &self.tom_3,
-            Instrument::PedalHiHat => &self.pedal_hihat,
+    /// BPM derived from the external MIDI clock (Timing Clock pulses);
+    /// `None` until two full quarter notes (48 pulses) have been received
+    pub fn get_external_bpm(&self) -> Option<f64> {
+        self.midi_input.as_ref().and_then(|midi_input| midi_input.get_external_bpm())
+    }
+
+    /// drain and return the non-note channel-voice messages (control
+    /// change, pitch bend, program change, aftertouch) received since the
+    /// last call
+    pub fn take_other_messages(&mut self) -> Vec<crate::midi_message::OtherChannelMessage> {
+        match &mut self.midi_input {
+            Some(midi_input) => midi_input.take_other_messages(),
+            None => Vec::new(),
+        }
+    }
+
+    /// drain and return the raw System Exclusive payloads received since
+    /// the last call
+    pub fn take_sysex_messages(&mut self) -> Vec<Vec<u8>> {
+        match &mut self.midi_input {
+            Some(midi_input) => midi_input.take_sysex_messages(),
+            None => Vec::new(),
         }
     }
 }
 
-fn get_midi_as_user_hits(midi_input: &MidiInput) -> Vec<UserHit> {
+fn get_midi_as_user_hits(midi_input: &mut MidiInput, drum_map: &DrumMap) -> Vec<UserHit> {
     let mut out: Vec<UserHit> = vec![];
 
-    // midi device: "MPK Mini Mk II"
-    let mpk_mini_mk_ii = InputConfigMidi {
-        closed_hi_hat: HashSet::from_iter(vec![44, 48]),
-        snare: HashSet::from_iter(vec![45, 49]),
-        kick: HashSet::from_iter(vec![46, 50]),
-        open_hi_hat: HashSet::from_iter(vec![47, 51]),
-        ride: HashSet::from_iter(vec![]),
-        crash: HashSet::from_iter(vec![]),
-        tom_1: HashSet::from_iter(vec![]),
-        tom_2: HashSet::from_iter(vec![]),
-        tom_3: HashSet::from_iter(vec![]),
-        pedal_hihat: HashSet::from_iter(vec![]),
-    };
-
-    // https://support.roland.com/hc/en-us/articles/360005173411-TD-17-Default-Factory-MIDI-Note-Map
-    let td17 = InputConfigMidi {
-        closed_hi_hat: HashSet::from_iter(vec![42, 22]),
-        snare: HashSet::from_iter(vec![38, 40, 37]),
-        kick: HashSet::from_iter(vec![36]),
-        open_hi_hat: HashSet::from_iter(vec![46, 26]),
-        ride: HashSet::from_iter(vec![51, 53, 59]),
-        crash: HashSet::from_iter(vec![49, 55, 57, 52]),
-        tom_1: HashSet::from_iter(vec![50, 48]),
-        tom_2: HashSet::from_iter(vec![47, 45]),
-        tom_3: HashSet::from_iter(vec![58, 43]),
-        pedal_hihat: HashSet::from_iter(vec![44]),
-    };
-
-    // https://support.roland.com/hc/en-us/articles/4407474950811-TD-27-Default-MIDI-Note-Map
-    let td27 = InputConfigMidi {
-        closed_hi_hat: HashSet::from_iter(vec![42, 22]),
-        snare: HashSet::from_iter(vec![38, 40, 37]),
-        kick: HashSet::from_iter(vec![36]),
-        open_hi_hat: HashSet::from_iter(vec![46, 26]),
-        ride: HashSet::from_iter(vec![51, 53, 59]),
-        crash: HashSet::from_iter(vec![49, 55, 57, 52]),
-        tom_1: HashSet::from_iter(vec![50, 48]),
-        tom_2: HashSet::from_iter(vec![47, 45]),
-        tom_3: HashSet::from_iter(vec![58, 43]),
-        pedal_hihat: HashSet::from_iter(vec![44]),
-    };
-
-    let alesis_nitro = InputConfigMidi {
-        closed_hi_hat: HashSet::from_iter(vec![42]),
-        snare: HashSet::from_iter(vec![38]),
-        kick: HashSet::from_iter(vec![36]),
-        open_hi_hat: HashSet::from_iter(vec![46, 23]),
-        ride: HashSet::from_iter(vec![]),
-        crash: HashSet::from_iter(vec![]),
-        tom_1: HashSet::from_iter(vec![]),
-        tom_2: HashSet::from_iter(vec![]),
-        tom_3: HashSet::from_iter(vec![]),
-        pedal_hihat: HashSet::from_iter(vec![]),
-    };
-
-    let ic_midi = match midi_input.get_device_name() {
-        s if s == "MPK Mini Mk II" => mpk_mini_mk_ii,
-        s if s.contains("TD-17") => td17,
-        s if s.contains("TD-27") => td27,
-        s if s.contains("Nitro") => alesis_nitro,
-        _ => {
-            log::warn!("warning: unknown midi device, using default of 'td27'");
-            td27
-        }
-    };
-
-    let pressed_midi = midi_input.get_pressed_buttons();
-
-    // for each pressed_midi, check if it's in the ic_midi and then add to out as a proper UserHit if so
-    for midi in pressed_midi {
-        println!("midi = {:?}", midi);
-        for ins in ALL_INSTRUMENTS.iter() {
-            if ic_midi.get_note_numbers(ins).contains(&midi.note_number) {
-                out.push(UserHit {
-                    instrument: *ins,
-                    raw_data: midi,
-                });
-            }
+    let onset_hits = midi_input.take_onset_hits();
+
+    // for each onset hit, look it up in the drum map and add to out as a proper UserHit if mapped
+    for hit in onset_hits {
+        println!("hit = {:?}", hit);
+        if let Some(instrument) = drum_map.lookup(hit.note_number) {
+            out.push(UserHit { instrument, hit });
         }
     }
 
@@ -0,0 +1,274 @@
+/*
+  Classify a raw midir message by its status byte instead of blindly
+  indexing message[0..2]. Channel-voice messages carry a variable number of
+  data bytes (0, 1, or 2), System Exclusive is variable-length, and a
+  device is free to omit repeated status bytes (running status) - all of
+  which panic or produce garbage if you assume every message is 3 bytes.
+*/
+
+use crate::midi::MidiInputDataRaw;
+
+/// A channel-voice message that isn't a note on/off - kept separate from
+/// `MidiInputDataRaw` so downstream code can match on note hits without
+/// also handling controllers, pitch bend, etc.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtherChannelMessage {
+    PolyphonicAftertouch {
+        channel: u8,
+        note_number: u8,
+        pressure: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    ChannelAftertouch {
+        channel: u8,
+        pressure: u8,
+    },
+    PitchBend {
+        channel: u8,
+        value: u16,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelMessage {
+    Note(MidiInputDataRaw),
+    Other(OtherChannelMessage),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParsedMidiMessage {
+    Channel(ChannelMessage),
+    /// System Real-Time (0xF8-0xFF): a single status byte, no data bytes.
+    RealTime(u8),
+    /// System Exclusive payload (everything between 0xF0 and the
+    /// terminating 0xF7, inclusive of neither). The trailing 0xF7 is
+    /// stripped if present - some devices omit it when the message is
+    /// chunked, so its absence isn't treated as an error.
+    SysEx(Vec<u8>),
+    /// System Common (0xF1-0xF7, including a lone 0xF7 that arrives outside
+    /// a SysEx message): not meaningful to this crate, but still a valid
+    /// message.
+    SystemCommon,
+    /// a status-less continuation byte arrived with no running status to
+    /// apply it to, or a channel-voice message was missing its data bytes
+    Incomplete,
+}
+
+/// Decode one message as delivered by `midir`'s connection callback.
+/// `running_status` carries the last channel-voice status byte seen across
+/// calls so devices that omit repeated status bytes still parse correctly;
+/// pass the same `&mut Option<u8>` for every message from a given
+/// connection.
+pub fn parse_message(
+    message: &[u8],
+    running_status: &mut Option<u8>,
+    timestamp: u64,
+    non_midi_timestamp_ms: u128,
+) -> ParsedMidiMessage {
+    if message.is_empty() {
+        return ParsedMidiMessage::Incomplete;
+    }
+
+    let (status, data) = if message[0] & 0x80 != 0 {
+        let status = message[0];
+        // real-time bytes can interleave with any other message and must
+        // not disturb the running status that message is using
+        if status < 0xF8 {
+            *running_status = if status < 0xF0 { Some(status) } else { None };
+        }
+        (status, &message[1..])
+    } else {
+        match *running_status {
+            Some(status) => (status, message),
+            None => return ParsedMidiMessage::Incomplete,
+        }
+    };
+
+    if status >= 0xF8 {
+        return ParsedMidiMessage::RealTime(status);
+    }
+    if status == 0xF0 {
+        let payload = match data.last() {
+            Some(0xF7) => &data[..data.len() - 1],
+            _ => data,
+        };
+        return ParsedMidiMessage::SysEx(payload.to_vec());
+    }
+    if status >= 0xF1 {
+        return ParsedMidiMessage::SystemCommon;
+    }
+
+    let channel = status & 0x0F;
+    match status & 0xF0 {
+        0x80 | 0x90 => {
+            let [note_number, note_velocity] = data else {
+                return ParsedMidiMessage::Incomplete;
+            };
+            ParsedMidiMessage::Channel(ChannelMessage::Note(MidiInputDataRaw::new(
+                *note_number,
+                timestamp,
+                non_midi_timestamp_ms,
+                status,
+                *note_velocity,
+            )))
+        }
+        0xA0 => {
+            let [note_number, pressure] = data else {
+                return ParsedMidiMessage::Incomplete;
+            };
+            other(OtherChannelMessage::PolyphonicAftertouch {
+                channel,
+                note_number: *note_number,
+                pressure: *pressure,
+            })
+        }
+        0xB0 => {
+            let [controller, value] = data else {
+                return ParsedMidiMessage::Incomplete;
+            };
+            other(OtherChannelMessage::ControlChange {
+                channel,
+                controller: *controller,
+                value: *value,
+            })
+        }
+        0xC0 => {
+            let [program] = data else {
+                return ParsedMidiMessage::Incomplete;
+            };
+            other(OtherChannelMessage::ProgramChange {
+                channel,
+                program: *program,
+            })
+        }
+        0xD0 => {
+            let [pressure] = data else {
+                return ParsedMidiMessage::Incomplete;
+            };
+            other(OtherChannelMessage::ChannelAftertouch {
+                channel,
+                pressure: *pressure,
+            })
+        }
+        0xE0 => {
+            let [lsb, msb] = data else {
+                return ParsedMidiMessage::Incomplete;
+            };
+            other(OtherChannelMessage::PitchBend {
+                channel,
+                value: ((*msb as u16) << 7) | *lsb as u16,
+            })
+        }
+        _ => ParsedMidiMessage::Incomplete,
+    }
+}
+
+fn other(msg: OtherChannelMessage) -> ParsedMidiMessage {
+    ParsedMidiMessage::Channel(ChannelMessage::Other(msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_message_rejects_an_empty_message() {
+        let mut running_status = None;
+        assert_eq!(
+            parse_message(&[], &mut running_status, 0, 0),
+            ParsedMidiMessage::Incomplete
+        );
+    }
+
+    #[test]
+    fn parse_message_rejects_a_channel_voice_message_missing_data_bytes() {
+        let mut running_status = None;
+        // note-on with only one of its two required data bytes
+        assert_eq!(
+            parse_message(&[0x90, 0x40], &mut running_status, 0, 0),
+            ParsedMidiMessage::Incomplete
+        );
+    }
+
+    #[test]
+    fn parse_message_strips_the_trailing_eox_byte_from_sysex() {
+        let mut running_status = None;
+        let message = [0xF0, 0x7E, 0x00, 0x06, 0x01, 0xF7];
+        match parse_message(&message, &mut running_status, 0, 0) {
+            ParsedMidiMessage::SysEx(payload) => {
+                assert_eq!(payload, vec![0x7E, 0x00, 0x06, 0x01]);
+            }
+            other => panic!("expected SysEx, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parse_message_accepts_sysex_missing_the_trailing_eox_byte() {
+        // some devices chunk a SysEx dump across messages and omit 0xF7
+        let mut running_status = None;
+        let message = [0xF0, 0x7E, 0x00];
+        match parse_message(&message, &mut running_status, 0, 0) {
+            ParsedMidiMessage::SysEx(payload) => {
+                assert_eq!(payload, vec![0x7E, 0x00]);
+            }
+            other => panic!("expected SysEx, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parse_message_applies_running_status_to_a_status_less_message() {
+        let mut running_status = None;
+        // a full note-on establishes running status...
+        let note_on = parse_message(&[0x90, 0x40, 0x64], &mut running_status, 0, 0);
+        assert!(matches!(
+            note_on,
+            ParsedMidiMessage::Channel(ChannelMessage::Note(_))
+        ));
+
+        // ...then a second note-on with the status byte omitted still parses
+        let second = parse_message(&[0x24, 0x50], &mut running_status, 0, 0);
+        match second {
+            ParsedMidiMessage::Channel(ChannelMessage::Note(v)) => {
+                assert_eq!(v.note_number, 0x24);
+                assert_eq!(v.velocity(), 0x50);
+            }
+            other => panic!("expected a note via running status, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parse_message_running_status_is_unaffected_by_interleaved_realtime_bytes() {
+        let mut running_status = None;
+        parse_message(&[0x90, 0x40, 0x64], &mut running_status, 0, 0);
+
+        // a Timing Clock byte can arrive mid-stream and must not clear
+        // running status for the channel-voice message that follows
+        assert_eq!(
+            parse_message(&[0xF8], &mut running_status, 0, 0),
+            ParsedMidiMessage::RealTime(0xF8)
+        );
+
+        let third = parse_message(&[0x24, 0x50], &mut running_status, 0, 0);
+        assert!(matches!(
+            third,
+            ParsedMidiMessage::Channel(ChannelMessage::Note(_))
+        ));
+    }
+
+    #[test]
+    fn parse_message_incomplete_status_less_byte_with_no_running_status() {
+        let mut running_status = None;
+        assert_eq!(
+            parse_message(&[0x24, 0x50], &mut running_status, 0, 0),
+            ParsedMidiMessage::Incomplete
+        );
+    }
+}
@@ -0,0 +1,156 @@
+/*
+  Send as well as receive: a metronome click on the beat grid, MIDI thru
+  (echoing incoming hits straight back out, for monitoring a silent e-kit
+  through a synth), and a panic/all-notes-off helper for shutdown.
+*/
+
+use midir;
+
+use crate::midi::OnsetHit;
+
+const NOTE_ON: u8 = 0x90;
+const NOTE_OFF: u8 = 0x80;
+const CONTROL_CHANGE: u8 = 0xB0;
+
+// https://www.midi.org/specifications-old/item/table-3-control-change-messages-data-bytes-2
+const CC_ALL_SOUND_OFF: u8 = 120;
+const CC_ALL_NOTES_OFF: u8 = 123;
+
+const NUM_MIDI_CHANNELS: u8 = 16;
+
+/// anything that can send a raw MIDI message. Reached through a trait
+/// object so send_click/echo_hit/panic's byte sequences can be exercised in
+/// tests without a real MIDI output device.
+trait MidiSink {
+    fn send(&mut self, message: &[u8]);
+}
+
+impl MidiSink for midir::MidiOutputConnection {
+    fn send(&mut self, message: &[u8]) {
+        midir::MidiOutputConnection::send(self, message).ok();
+    }
+}
+
+pub struct MidiOutput {
+    connection: Box<dyn MidiSink>,
+}
+
+impl MidiOutput {
+    pub fn new() -> Option<Self> {
+        let midi_output = midir::MidiOutput::new("Output device").unwrap();
+        let output_port = midi_output.ports().into_iter().next()?;
+        let connection = midi_output
+            .connect(&output_port, "output connection")
+            .expect("can't connect to midi output device");
+
+        Some(Self {
+            connection: Box::new(connection),
+        })
+    }
+
+    /// emit a metronome click: a short note-on/note-off on `channel`/`note`,
+    /// called once per beat derived from the tempo/quantization grid
+    pub fn send_click(&mut self, channel: u8, note: u8, velocity: u8) {
+        let channel = channel & 0x0F;
+        self.connection.send(&[NOTE_ON | channel, note, velocity]);
+        self.connection.send(&[NOTE_OFF | channel, note, 0]);
+    }
+
+    /// MIDI thru: echo an incoming hit straight back out on `channel`, for
+    /// monitoring a silent e-kit through a synth
+    pub fn echo_hit(&mut self, channel: u8, hit: &OnsetHit) {
+        let channel = channel & 0x0F;
+        self.connection
+            .send(&[NOTE_ON | channel, hit.note_number, hit.velocity]);
+        self.connection.send(&[NOTE_OFF | channel, hit.note_number, 0]);
+    }
+
+    /// send CC 123 (All Notes Off) and CC 120 (All Sound Off) on every
+    /// channel, so stuck notes don't ring out after shutdown
+    pub fn panic(&mut self) {
+        for channel in 0..NUM_MIDI_CHANNELS {
+            self.connection
+                .send(&[CONTROL_CHANGE | channel, CC_ALL_NOTES_OFF, 0]);
+            self.connection
+                .send(&[CONTROL_CHANGE | channel, CC_ALL_SOUND_OFF, 0]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct RecordingSink {
+        sent: Rc<RefCell<Vec<Vec<u8>>>>,
+    }
+
+    impl MidiSink for RecordingSink {
+        fn send(&mut self, message: &[u8]) {
+            self.sent.borrow_mut().push(message.to_vec());
+        }
+    }
+
+    fn midi_output_with_recording_sink() -> (MidiOutput, RecordingSink) {
+        let sink = RecordingSink::default();
+        (
+            MidiOutput {
+                connection: Box::new(sink.clone()),
+            },
+            sink,
+        )
+    }
+
+    #[test]
+    fn send_click_emits_a_note_on_then_note_off() {
+        let (mut midi_output, sink) = midi_output_with_recording_sink();
+        midi_output.send_click(0, 75, 100);
+
+        assert_eq!(
+            *sink.sent.borrow(),
+            vec![vec![NOTE_ON, 75, 100], vec![NOTE_OFF, 75, 0]]
+        );
+    }
+
+    #[test]
+    fn send_click_masks_the_channel_to_four_bits() {
+        let (mut midi_output, sink) = midi_output_with_recording_sink();
+        midi_output.send_click(0x1F, 75, 100);
+
+        assert_eq!(sink.sent.borrow()[0][0], NOTE_ON | 0x0F);
+    }
+
+    #[test]
+    fn echo_hit_emits_a_note_on_then_note_off_for_the_hit() {
+        let (mut midi_output, sink) = midi_output_with_recording_sink();
+        midi_output.echo_hit(
+            2,
+            &OnsetHit {
+                note_number: 38,
+                velocity: 90,
+                onset_timestamp_ms: 0,
+            },
+        );
+
+        assert_eq!(
+            *sink.sent.borrow(),
+            vec![vec![NOTE_ON | 2, 38, 90], vec![NOTE_OFF | 2, 38, 0]]
+        );
+    }
+
+    #[test]
+    fn panic_sends_all_notes_off_and_all_sound_off_on_every_channel() {
+        let (mut midi_output, sink) = midi_output_with_recording_sink();
+        midi_output.panic();
+
+        let sent = sink.sent.borrow();
+        assert_eq!(sent.len(), NUM_MIDI_CHANNELS as usize * 2);
+        for channel in 0..NUM_MIDI_CHANNELS {
+            assert!(sent.contains(&vec![CONTROL_CHANGE | channel, CC_ALL_NOTES_OFF, 0]));
+            assert!(sent.contains(&vec![CONTROL_CHANGE | channel, CC_ALL_SOUND_OFF, 0]));
+        }
+    }
+}
@@ -0,0 +1,114 @@
+/*
+  Tempo-aware beat quantization: compare a hit's timestamp against the
+  nearest slot on the BEATS_PER_LOOP grid and report the signed timing
+  error, so the game can show an early/late meter and an accuracy score.
+*/
+
+use crate::midi_input_handler::{UserHit, BEATS_PER_LOOP};
+
+/// Where a hit landed relative to the beat grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuantizedHit {
+    /// nearest beat slot, in `0..BEATS_PER_LOOP`
+    pub beat_index: u32,
+    /// signed distance from the nearest beat, in ms. Negative is early,
+    /// positive is late.
+    pub error_ms: f64,
+}
+
+/// A fixed tempo grid, anchored to the start of a loop.
+pub struct BeatGrid {
+    loop_start_ms: u128,
+    bpm: f64,
+}
+
+impl BeatGrid {
+    pub fn new(loop_start_ms: u128, bpm: f64) -> Self {
+        Self { loop_start_ms, bpm }
+    }
+
+    fn ms_per_beat(&self) -> f64 {
+        60_000. / self.bpm
+    }
+
+    /// Convert a hit's onset timestamp into a fractional beat position and
+    /// the signed timing error against the nearest grid slot.
+    pub fn quantize_ms(&self, hit_ms: u128) -> QuantizedHit {
+        let ms_per_beat = self.ms_per_beat();
+        let loop_length_ms = ms_per_beat * BEATS_PER_LOOP;
+
+        // rem_euclid so a timestamp before loop_start_ms (or a hit that
+        // lands exactly on the loop boundary) still wraps into [0, loop_length_ms)
+        let elapsed = (hit_ms as f64 - self.loop_start_ms as f64).rem_euclid(loop_length_ms);
+        let beat_pos = elapsed / ms_per_beat;
+        let nearest = beat_pos.round();
+        let error_ms = (beat_pos - nearest) * ms_per_beat;
+
+        // a hit just before the loop boundary rounds to BEATS_PER_LOOP, not
+        // 0, so wrap it back onto the grid
+        let beat_index = (nearest as i64).rem_euclid(BEATS_PER_LOOP as i64) as u32;
+
+        QuantizedHit {
+            beat_index,
+            error_ms,
+        }
+    }
+
+    pub fn quantize(&self, hit: &UserHit) -> QuantizedHit {
+        self.quantize_ms(hit.hit.onset_timestamp_ms)
+    }
+
+    /// Which beat slot `now_ms` currently falls in, e.g. to fire a
+    /// metronome click once per beat. Unlike `quantize_ms`, this floors
+    /// rather than rounds, since "current beat" shouldn't jump ahead of
+    /// `now_ms`.
+    pub fn current_beat(&self, now_ms: u128) -> u32 {
+        let ms_per_beat = self.ms_per_beat();
+        let loop_length_ms = ms_per_beat * BEATS_PER_LOOP;
+        let elapsed = (now_ms as f64 - self.loop_start_ms as f64).rem_euclid(loop_length_ms);
+        (elapsed / ms_per_beat).floor() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantize_wraps_a_hit_just_before_the_loop_boundary_to_beat_zero() {
+        let bpm = 120.;
+        let grid = BeatGrid::new(0, bpm);
+        let ms_per_beat = 60_000. / bpm;
+        let loop_length_ms = ms_per_beat * BEATS_PER_LOOP;
+
+        // a few ms before the loop wraps back around to beat 0
+        let hit_ms = (loop_length_ms - 2.).round() as u128;
+        let quantized = grid.quantize_ms(hit_ms);
+
+        assert_eq!(quantized.beat_index, 0);
+        assert!(quantized.error_ms.abs() <= 2.5);
+    }
+
+    #[test]
+    fn quantize_snaps_an_exact_beat_to_zero_error() {
+        let bpm = 120.;
+        let grid = BeatGrid::new(1_000, bpm);
+        let ms_per_beat = 60_000. / bpm;
+
+        let quantized = grid.quantize_ms(1_000 + (ms_per_beat * 3.) as u128);
+
+        assert_eq!(quantized.beat_index, 3);
+        assert_eq!(quantized.error_ms, 0.);
+    }
+
+    #[test]
+    fn current_beat_floors_instead_of_rounding() {
+        let bpm = 120.;
+        let grid = BeatGrid::new(0, bpm);
+        let ms_per_beat = 60_000. / bpm;
+
+        // just shy of beat 2, should still report beat 1
+        let now_ms = (ms_per_beat * 2. - 1.) as u128;
+        assert_eq!(grid.current_beat(now_ms), 1);
+    }
+}
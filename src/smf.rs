@@ -0,0 +1,247 @@
+/*
+  Record a session of completed hits and export it as a type-0 Standard
+  MIDI File, so a take can be opened and reviewed in any DAW. Recording
+  needs each hit's hold duration (for note-off placement), which isn't
+  known until release, so it consumes `CompletedHit`s directly rather than
+  the onset-only `UserHit`s the game loop uses for input timing.
+
+  Writes the SMF chunks directly rather than pulling in a midi-file crate:
+  an `MThd` header chunk, followed by a single `MTrk` chunk whose events are
+  delta-time-prefixed note-on/note-off messages plus a tempo meta event and
+  an end-of-track meta event.
+*/
+
+use std::fs::File;
+use std::io::{self, Write};
+
+use crate::midi::CompletedHit;
+
+/// ticks per quarter note
+const DIVISION: u16 = 480;
+
+const NOTE_ON_CHANNEL_0: u8 = 0x90;
+const NOTE_OFF_CHANNEL_0: u8 = 0x80;
+
+#[derive(Debug, Clone, Copy)]
+struct RecordedHit {
+    note_number: u8,
+    velocity: u8,
+    onset_ms: u128,
+    release_ms: u128,
+}
+
+/// Buffers every completed hit in a session so it can be exported as a
+/// type-0 Standard MIDI File.
+#[derive(Default)]
+pub struct Recorder {
+    hits: Vec<RecordedHit>,
+    // the first recorded hit's onset, used to rebase every timestamp so the
+    // take starts near tick 0 instead of at the Unix epoch
+    session_start_ms: Option<u128>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self {
+            hits: Vec::new(),
+            session_start_ms: None,
+        }
+    }
+
+    pub fn record_hit(&mut self, hit: &CompletedHit) {
+        let session_start_ms = *self.session_start_ms.get_or_insert(hit.onset_timestamp_ms);
+        self.hits.push(RecordedHit {
+            note_number: hit.note_number,
+            velocity: hit.velocity,
+            onset_ms: hit.onset_timestamp_ms.saturating_sub(session_start_ms),
+            release_ms: hit.release_timestamp_ms.saturating_sub(session_start_ms),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hits.is_empty()
+    }
+
+    /// Write the recorded session to `path` as a type-0 Standard MIDI File,
+    /// using `bpm` to convert hit timestamps (ms) into ticks.
+    pub fn export_smf(&self, path: &str, bpm: f64) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        let track_data = build_track_data(&self.hits, bpm);
+
+        write_mthd(&mut file, 0, 1, DIVISION)?;
+        write_mtrk(&mut file, &track_data)?;
+        Ok(())
+    }
+}
+
+fn ms_to_ticks(ms: u128, bpm: f64) -> u32 {
+    let ms_per_beat = 60_000. / bpm;
+    (ms as f64 / ms_per_beat * DIVISION as f64).round() as u32
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TrackEvent {
+    tick: u32,
+    status: u8,
+    note_number: u8,
+    velocity: u8,
+}
+
+fn build_track_data(hits: &[RecordedHit], bpm: f64) -> Vec<u8> {
+    let mut events: Vec<TrackEvent> = Vec::with_capacity(hits.len() * 2);
+    for hit in hits {
+        events.push(TrackEvent {
+            tick: ms_to_ticks(hit.onset_ms, bpm),
+            status: NOTE_ON_CHANNEL_0,
+            note_number: hit.note_number,
+            velocity: hit.velocity,
+        });
+        events.push(TrackEvent {
+            tick: ms_to_ticks(hit.release_ms, bpm),
+            status: NOTE_OFF_CHANNEL_0,
+            note_number: hit.note_number,
+            velocity: 0,
+        });
+    }
+    // stable sort so a note-on and note-off landing on the same tick keep
+    // their recorded order (note-on before note-off)
+    events.sort_by_key(|e| e.tick);
+
+    let mut data = Vec::new();
+
+    // tempo meta event at tick 0: FF 51 03 <24-bit microseconds per quarter note>
+    let micros_per_quarter = (60_000_000. / bpm).round() as u32;
+    data.extend(write_vlq(0));
+    data.extend_from_slice(&[0xFF, 0x51, 0x03]);
+    data.extend_from_slice(&micros_per_quarter.to_be_bytes()[1..]);
+
+    let mut prev_tick = 0u32;
+    for event in events {
+        let delta = event.tick.saturating_sub(prev_tick);
+        prev_tick = event.tick;
+        data.extend(write_vlq(delta));
+        data.push(event.status);
+        data.push(event.note_number);
+        data.push(event.velocity);
+    }
+
+    // end of track meta event
+    data.extend(write_vlq(0));
+    data.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+
+    data
+}
+
+/// MIDI variable-length quantity: 7 bits per byte, high bit set on all but
+/// the last byte.
+fn write_vlq(mut value: u32) -> Vec<u8> {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.push(((value & 0x7F) as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+fn write_mthd(file: &mut File, format: u16, ntrks: u16, division: u16) -> io::Result<()> {
+    file.write_all(b"MThd")?;
+    file.write_all(&6u32.to_be_bytes())?;
+    file.write_all(&format.to_be_bytes())?;
+    file.write_all(&ntrks.to_be_bytes())?;
+    file.write_all(&division.to_be_bytes())?;
+    Ok(())
+}
+
+fn write_mtrk(file: &mut File, track_data: &[u8]) -> io::Result<()> {
+    file.write_all(b"MTrk")?;
+    file.write_all(&(track_data.len() as u32).to_be_bytes())?;
+    file.write_all(track_data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    // canonical examples from the Standard MIDI File spec
+    #[test]
+    fn vlq_matches_spec_examples() {
+        assert_eq!(write_vlq(0x00000000), vec![0x00]);
+        assert_eq!(write_vlq(0x00000040), vec![0x40]);
+        assert_eq!(write_vlq(0x0000007F), vec![0x7F]);
+        assert_eq!(write_vlq(0x00000080), vec![0x81, 0x00]);
+        assert_eq!(write_vlq(0x00002000), vec![0xC0, 0x00]);
+        assert_eq!(write_vlq(0x00003FFF), vec![0xFF, 0x7F]);
+        assert_eq!(write_vlq(0x001FFFFF), vec![0xFF, 0xFF, 0x7F]);
+        assert_eq!(write_vlq(0x08000000), vec![0xC0, 0x80, 0x80, 0x00]);
+        assert_eq!(write_vlq(0x0FFFFFFF), vec![0xFF, 0xFF, 0xFF, 0x7F]);
+    }
+
+    #[test]
+    fn ms_to_ticks_converts_one_beat_at_120_bpm_to_one_quarter_note() {
+        // at 120bpm a beat is 500ms; one quarter note is DIVISION ticks
+        assert_eq!(ms_to_ticks(500, 120.), DIVISION as u32);
+        assert_eq!(ms_to_ticks(0, 120.), 0);
+    }
+
+    #[test]
+    fn recorder_rebases_timestamps_to_the_first_hit_instead_of_the_epoch() {
+        let mut recorder = Recorder::new();
+        let epoch_ms = 1_700_000_000_000u128;
+        recorder.record_hit(&CompletedHit {
+            note_number: 38,
+            velocity: 100,
+            onset_timestamp_ms: epoch_ms,
+            release_timestamp_ms: epoch_ms + 50,
+            duration_ms: 50,
+        });
+        recorder.record_hit(&CompletedHit {
+            note_number: 38,
+            velocity: 100,
+            onset_timestamp_ms: epoch_ms + 500,
+            release_timestamp_ms: epoch_ms + 550,
+            duration_ms: 50,
+        });
+
+        assert_eq!(recorder.hits[0].onset_ms, 0);
+        assert_eq!(recorder.hits[1].onset_ms, 500);
+    }
+
+    #[test]
+    fn mthd_chunk_has_the_documented_byte_layout() {
+        let path = std::env::temp_dir().join(format!("smf_mthd_test_{}.mid", std::process::id()));
+        {
+            let mut file = File::create(&path).unwrap();
+            write_mthd(&mut file, 0, 1, DIVISION).unwrap();
+        }
+        let mut bytes = Vec::new();
+        File::open(&path).unwrap().read_to_end(&mut bytes).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(&bytes[0..4], b"MThd");
+        assert_eq!(&bytes[4..8], &6u32.to_be_bytes());
+        assert_eq!(&bytes[8..10], &0u16.to_be_bytes()); // format
+        assert_eq!(&bytes[10..12], &1u16.to_be_bytes()); // ntrks
+        assert_eq!(&bytes[12..14], &DIVISION.to_be_bytes());
+    }
+
+    #[test]
+    fn mtrk_chunk_prefixes_track_data_with_its_length() {
+        let path = std::env::temp_dir().join(format!("smf_mtrk_test_{}.mid", std::process::id()));
+        let track_data = vec![0x00, 0xFF, 0x2F, 0x00];
+        {
+            let mut file = File::create(&path).unwrap();
+            write_mtrk(&mut file, &track_data).unwrap();
+        }
+        let mut bytes = Vec::new();
+        File::open(&path).unwrap().read_to_end(&mut bytes).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(&bytes[0..4], b"MTrk");
+        assert_eq!(&bytes[4..8], &(track_data.len() as u32).to_be_bytes());
+        assert_eq!(&bytes[8..], track_data.as_slice());
+    }
+}